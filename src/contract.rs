@@ -1,11 +1,21 @@
 use cosmwasm::errors::{contract_err, unauthorized, Result};
 use cosmwasm::traits::{Api, Extern, Storage};
-use cosmwasm::types::{Env, HumanAddr, Response};
+use cosmwasm::types::{coin, CanonicalAddr, CosmosMsg, Env, HumanAddr, Response, Uint128};
 
-use cw_storage::serialize;
+use cw_storage::{deserialize, serialize};
 
-use crate::msg::{EcostateResponse, HandleMsg, InitMsg, QueryMsg, StateResponse};
-use crate::state::{config, config_read, State};
+use crate::msg::{
+    EcostateResponse, HandleMsg, HistoryResponse, InitMsg, MigrateMsg, QueryMsg, StateResponse,
+    VestingResponse, ViewingKeyResponse,
+};
+use crate::state::{
+    config, config_legacy_read, config_read, history, history_read, viewing_keys,
+    viewing_keys_read, ContractStatus, HistoryEntry, LegacyHistoryEntry, PayoutCurve, State,
+};
+use crate::viewing_key::{ct_eq, generate_viewing_key, hash_viewing_key};
+
+const DEFAULT_HISTORY_LIMIT: u32 = 10;
+const MAX_HISTORY_LIMIT: u32 = 30;
 
 pub fn init<S: Storage, A: Api>(
     deps: &mut Extern<S, A>,
@@ -18,9 +28,17 @@ pub fn init<S: Storage, A: Api>(
         oracle: deps.api.canonical_address(&msg.oracle)?,
         region: msg.region,
         total_tokens: msg.total_tokens,
-        released_tokens: 0,
+        released_tokens: Uint128::default(),
         owner: env.message.signer,
-        is_locked: false,
+        status: ContractStatus::Operational,
+        denom: msg.denom,
+        payout_curve: msg.payout_curve,
+        start: msg.start,
+        cliff: msg.cliff,
+        deadline: msg.deadline,
+        pending_release: Uint128::default(),
+        prng_seed: msg.prng_seed,
+        history_len: 0,
     };
 
     config(&mut deps.storage).save(&state)?;
@@ -28,32 +46,102 @@ pub fn init<S: Storage, A: Api>(
     Ok(Response::default())
 }
 
+/// Upgrades a contract instantiated before token amounts were widened from `i64` to
+/// `Uint128`, rewriting its stored state and its history entries into the current schema.
+pub fn migrate<S: Storage, A: Api>(
+    deps: &mut Extern<S, A>,
+    _env: Env,
+    _msg: MigrateMsg,
+) -> Result<Response> {
+    let legacy = config_legacy_read(&deps.storage).load()?;
+
+    let migrated = State {
+        region: legacy.region,
+        beneficiary: legacy.beneficiary,
+        owner: legacy.owner,
+        oracle: legacy.oracle,
+        ecostate: legacy.ecostate,
+        total_tokens: Uint128::from(non_negative(legacy.total_tokens)?),
+        released_tokens: Uint128::from(non_negative(legacy.released_tokens)?),
+        status: legacy.status,
+        denom: legacy.denom,
+        payout_curve: legacy.payout_curve,
+        start: legacy.start,
+        cliff: legacy.cliff,
+        deadline: legacy.deadline,
+        pending_release: Uint128::from(non_negative(legacy.pending_release)?),
+        prng_seed: legacy.prng_seed,
+        history_len: legacy.history_len,
+    };
+
+    migrate_history(&mut deps.storage, migrated.history_len)?;
+
+    config(&mut deps.storage).save(&migrated)?;
+
+    Ok(Response::default())
+}
+
+/// Rewrites every history entry recorded under the pre-`Uint128` schema (`payout_amount: i64`)
+/// into the current schema, so `query_history` doesn't fail to deserialize entries that were
+/// appended before this contract was migrated.
+fn migrate_history<S: Storage>(storage: &mut S, history_len: u64) -> Result<()> {
+    for seq in 0..history_len {
+        let key = seq.to_be_bytes();
+        let raw = match history_read(storage).get(&key) {
+            Some(raw) => raw,
+            None => continue,
+        };
+        let legacy: LegacyHistoryEntry = deserialize(&raw)?;
+        let migrated = HistoryEntry {
+            height: legacy.height,
+            time: legacy.time,
+            oracle: legacy.oracle,
+            old_ecostate: legacy.old_ecostate,
+            new_ecostate: legacy.new_ecostate,
+            payout_amount: Uint128::from(non_negative(legacy.payout_amount)?),
+        };
+        history(storage).set(&key, &serialize(&migrated)?);
+    }
+
+    Ok(())
+}
+
+fn non_negative(amount: i64) -> Result<u128> {
+    if amount < 0 {
+        contract_err("Cannot migrate a negative token amount")
+    } else {
+        Ok(amount as u128)
+    }
+}
+
 pub fn handle<S: Storage, A: Api>(
     deps: &mut Extern<S, A>,
     env: Env,
     msg: HandleMsg,
 ) -> Result<Response> {
     match msg {
-        HandleMsg::Lock {} => try_set_lock(deps, env, true),
-        HandleMsg::Unlock {} => try_set_lock(deps, env, false),
+        HandleMsg::SetStatus { status } => try_set_status(deps, env, status),
         HandleMsg::ChangeBeneficiary { beneficiary } => {
             try_change_beneficiary(deps, env, beneficiary)
         }
         HandleMsg::UpdateEcostate { ecostate } => try_update_ecostate(deps, env, ecostate),
         HandleMsg::TransferOwnership { owner } => try_transfer_ownership(deps, env, owner),
+        HandleMsg::Reclaim {} => try_reclaim(deps, env),
+        HandleMsg::SetViewingKey { key } => try_set_viewing_key(deps, env, key),
+        HandleMsg::CreateViewingKey { entropy } => try_create_viewing_key(deps, env, entropy),
     }
 }
 
-fn try_set_lock<S: Storage, A: Api>(
+fn try_set_status<S: Storage, A: Api>(
     deps: &mut Extern<S, A>,
     env: Env,
-    locked: bool,
+    status: ContractStatus,
 ) -> Result<Response> {
     config(&mut deps.storage).update(&|mut state| {
         if env.message.signer != state.owner {
             unauthorized()
         } else {
-            state.is_locked = locked;
+            state.status = status.clone();
             Ok(state)
         }
     })?;
@@ -68,7 +156,7 @@ fn try_change_beneficiary<S: Storage, A: Api>(
 ) -> Result<Response> {
     let api = deps.api;
     config(&mut deps.storage).update(&|mut state| {
-        check_lock(&state)?;
+        check_not_stopped(&state)?;
         if env.message.signer != state.owner {
             unauthorized()
         } else {
@@ -86,42 +174,249 @@ fn try_update_ecostate<S: Storage, A: Api>(
     ecostate: i64,
 ) -> Result<Response> {
     let mut state = config(&mut deps.storage).load()?;
-    check_lock(&state)?;
+    check_not_stopped(&state)?;
 
     if env.message.signer != state.oracle {
-        unauthorized()?;
-    } else {
-        valid_ecostate(&ecostate)?;
+        return unauthorized();
+    }
+
+    valid_ecostate(&ecostate)?;
 
-        let ecostate_delta = ecostate - state.ecostate;
-        state.ecostate = ecostate;
+    let old_ecostate = state.ecostate;
+    state.ecostate = ecostate;
 
-        if ecostate_delta > 0 {
-            state = execute_payout(state, ecostate_delta)?;
+    let mut messages = vec![];
+    let mut payout_amount = Uint128::default();
+    if ecostate > old_ecostate && !matches!(state.status, ContractStatus::StopPayouts { .. }) {
+        let curve_amount = curve_payout_amount(&state.payout_curve, old_ecostate, ecostate)?;
+
+        if env.block.time < state.start.saturating_add(state.cliff) {
+            // Before the cliff: the gain is owed, but nothing is released yet.
+            state.pending_release = checked_add_u128(state.pending_release, curve_amount)?;
+        } else if env.block.time < state.deadline {
+            let due = checked_add_u128(state.pending_release, curve_amount)?;
+            let (new_state, disbursed, payout_msg) = execute_payout(deps, &env, state, due)?;
+            state = new_state;
+            // Anything execute_payout couldn't disburse (escrow or total_tokens shortfall)
+            // stays owed, so it's still reflected in GetVesting and can be paid out later.
+            state.pending_release = checked_sub_u128(due, disbursed)?;
+            payout_amount = disbursed;
+            messages.extend(payout_msg);
         }
+        // After the deadline, gains are recorded but no longer accrue a payout;
+        // any unreleased escrow is left for the owner to reclaim.
+    }
+
+    append_history(
+        &mut deps.storage,
+        &mut state,
+        &env,
+        old_ecostate,
+        ecostate,
+        payout_amount,
+    )?;
+
+    config(&mut deps.storage).save(&state)?;
 
+    Ok(Response {
+        messages,
+        ..Response::default()
+    })
+}
+
+/// Appends a record of this update to the audit log, using `state.history_len` as the next
+/// sequence key, then advances the counter so the next entry doesn't collide.
+fn append_history<S: Storage>(
+    storage: &mut S,
+    state: &mut State,
+    env: &Env,
+    old_ecostate: i64,
+    new_ecostate: i64,
+    payout_amount: Uint128,
+) -> Result<()> {
+    let entry = HistoryEntry {
+        height: env.block.height,
+        time: env.block.time,
+        oracle: state.oracle.clone(),
+        old_ecostate,
+        new_ecostate,
+        payout_amount,
+    };
+
+    history(storage).set(&state.history_len.to_be_bytes(), &serialize(&entry)?);
+    state.history_len += 1;
+
+    Ok(())
+}
+
+fn execute_payout<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    env: &Env,
+    mut state: State,
+    amount: Uint128,
+) -> Result<(State, Uint128, Option<CosmosMsg>)> {
+    let escrow = escrow_balance(env, &state.denom);
+    let payout_amount = amount.min(state.total_tokens).min(escrow);
+
+    if payout_amount.u128() == 0 {
+        return Ok((state, Uint128::default(), None));
+    }
+
+    state.total_tokens = checked_sub_u128(state.total_tokens, payout_amount)?;
+    state.released_tokens = checked_add_u128(state.released_tokens, payout_amount)?;
+
+    let beneficiary = deps.api.human_address(&state.beneficiary)?;
+    let msg = CosmosMsg::Send {
+        from_address: env.contract.address.clone(),
+        to_address: beneficiary,
+        amount: coin(&payout_amount.to_string(), &state.denom),
+    };
+
+    Ok((state, payout_amount, Some(msg)))
+}
+
+fn try_reclaim<S: Storage, A: Api>(deps: &mut Extern<S, A>, env: Env) -> Result<Response> {
+    let mut state = config(&mut deps.storage).load()?;
+    check_not_stopped(&state)?;
+
+    if env.message.signer != state.owner {
+        return unauthorized();
+    }
+
+    if env.block.time < state.deadline {
+        return contract_err("Cannot reclaim escrow before the vesting deadline");
+    }
+
+    let escrow = escrow_balance(&env, &state.denom);
+    let reclaim_amount = state.total_tokens.min(escrow);
+
+    if reclaim_amount.u128() == 0 {
         config(&mut deps.storage).save(&state)?;
+        return Ok(Response::default());
     }
 
+    state.total_tokens = checked_sub_u128(state.total_tokens, reclaim_amount)?;
+
+    let owner = deps.api.human_address(&state.owner)?;
+    let msg = CosmosMsg::Send {
+        from_address: env.contract.address.clone(),
+        to_address: owner,
+        amount: coin(&reclaim_amount.to_string(), &state.denom),
+    };
+
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(Response {
+        messages: vec![msg],
+        ..Response::default()
+    })
+}
+
+fn try_set_viewing_key<S: Storage, A: Api>(
+    deps: &mut Extern<S, A>,
+    env: Env,
+    key: String,
+) -> Result<Response> {
+    let hashed = hash_viewing_key(&key);
+    viewing_keys(&mut deps.storage).set(env.message.signer.as_slice(), &hashed);
+
     Ok(Response::default())
 }
 
-fn execute_payout(mut state: State, ecostate_delta: i64) -> Result<State> {
-    let payout_amount = ecostate_delta;
+fn try_create_viewing_key<S: Storage, A: Api>(
+    deps: &mut Extern<S, A>,
+    env: Env,
+    entropy: String,
+) -> Result<Response> {
+    let state = config_read(&deps.storage).load()?;
+    let key = generate_viewing_key(&state.prng_seed, entropy.as_bytes());
+    viewing_keys(&mut deps.storage).set(env.message.signer.as_slice(), &hash_viewing_key(&key));
+
+    let resp = ViewingKeyResponse { key };
+    Ok(Response {
+        data: Some(serialize(&resp)?),
+        ..Response::default()
+    })
+}
+
+fn escrow_balance(env: &Env, denom: &str) -> Uint128 {
+    env.contract
+        .balance
+        .iter()
+        .find(|c| c.denom == denom)
+        .and_then(|c| c.amount.parse::<u128>().ok())
+        .map(Uint128::from)
+        .unwrap_or_default()
+}
+
+/// Converts an ecostate gain into a token amount according to the configured curve.
+/// All arithmetic is checked; overflow yields a `contract_err` rather than a panic.
+fn curve_payout_amount(
+    curve: &PayoutCurve,
+    old_ecostate: i64,
+    new_ecostate: i64,
+) -> Result<Uint128> {
+    let delta = checked_sub(new_ecostate, old_ecostate)?;
 
-    if payout_amount < 0 {
-        contract_err("Error: cannot payout negative ammount")?;
+    let raw = match curve {
+        PayoutCurve::Linear { rate_num, rate_den } => {
+            checked_div(checked_mul(delta, *rate_num)?, *rate_den)?
+        }
+        PayoutCurve::Quadratic { rate_num, rate_den } => {
+            let new_sq = checked_mul(new_ecostate, new_ecostate)?;
+            let old_sq = checked_mul(old_ecostate, old_ecostate)?;
+            let sq_delta = checked_sub(new_sq, old_sq)?;
+            checked_div(checked_mul(sq_delta, *rate_num)?, *rate_den)?
+        }
+        PayoutCurve::Exponential { base_num, base_den } => {
+            let mut amount: i64 = 1;
+            for _ in 0..delta {
+                amount = checked_div(checked_mul(amount, *base_num)?, *base_den)?;
+            }
+            amount
+        }
+    };
+
+    if raw < 0 {
+        return contract_err("Computed negative payout amount");
     }
 
-    if state.total_tokens >= payout_amount {
-        state.total_tokens -= payout_amount;
-        state.released_tokens += payout_amount;
-    } else {
-        state.released_tokens += state.total_tokens;
-        state.total_tokens = 0;
+    Ok(Uint128::from(raw as u128))
+}
+
+fn checked_mul(a: i64, b: i64) -> Result<i64> {
+    match a.checked_mul(b) {
+        Some(v) => Ok(v),
+        None => contract_err("Overflow computing payout amount"),
+    }
+}
+
+fn checked_div(a: i64, b: i64) -> Result<i64> {
+    match a.checked_div(b) {
+        Some(v) => Ok(v),
+        None => contract_err("Overflow computing payout amount"),
     }
+}
 
-    Ok(state)
+fn checked_sub(a: i64, b: i64) -> Result<i64> {
+    match a.checked_sub(b) {
+        Some(v) => Ok(v),
+        None => contract_err("Overflow computing payout amount"),
+    }
+}
+
+fn checked_add_u128(a: Uint128, b: Uint128) -> Result<Uint128> {
+    match a.u128().checked_add(b.u128()) {
+        Some(v) => Ok(Uint128::from(v)),
+        None => contract_err("Overflow computing payout amount"),
+    }
+}
+
+fn checked_sub_u128(a: Uint128, b: Uint128) -> Result<Uint128> {
+    match a.u128().checked_sub(b.u128()) {
+        Some(v) => Ok(Uint128::from(v)),
+        None => contract_err("Underflow computing payout amount"),
+    }
 }
 
 fn try_transfer_ownership<S: Storage, A: Api>(
@@ -131,7 +426,7 @@ fn try_transfer_ownership<S: Storage, A: Api>(
 ) -> Result<Response> {
     let api = deps.api;
     config(&mut deps.storage).update(&|mut state| {
-        check_lock(&state)?;
+        check_not_stopped(&state)?;
         if env.message.signer != state.owner {
             unauthorized()
         } else {
@@ -145,20 +440,37 @@ fn try_transfer_ownership<S: Storage, A: Api>(
 
 pub fn query<S: Storage, A: Api>(deps: &Extern<S, A>, msg: QueryMsg) -> Result<Vec<u8>> {
     match msg {
-        QueryMsg::GetState {} => query_state(deps),
-        QueryMsg::GetEcostate {} => query_ecostate(deps),
+        QueryMsg::GetState { address, key } => query_state(deps, address, key),
+        QueryMsg::GetEcostate { address, key } => query_ecostate(deps, address, key),
+        QueryMsg::GetVesting { address, key } => query_vesting(deps, address, key),
+        QueryMsg::GetHistory {
+            address,
+            key,
+            start_after,
+            limit,
+        } => query_history(deps, address, key, start_after, limit),
     }
 }
 
-fn query_state<S: Storage, A: Api>(deps: &Extern<S, A>) -> Result<Vec<u8>> {
+fn query_state<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    address: HumanAddr,
+    key: String,
+) -> Result<Vec<u8>> {
     let state = config_read(&deps.storage).load()?;
+    authenticate(deps, &state, &address, &key)?;
 
     let resp = StateResponse { state };
     serialize(&resp)
 }
 
-fn query_ecostate<S: Storage, A: Api>(deps: &Extern<S, A>) -> Result<Vec<u8>> {
+fn query_ecostate<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    address: HumanAddr,
+    key: String,
+) -> Result<Vec<u8>> {
     let state = config_read(&deps.storage).load()?;
+    authenticate(deps, &state, &address, &key)?;
 
     let resp = EcostateResponse {
         ecostate: state.ecostate,
@@ -166,6 +478,82 @@ fn query_ecostate<S: Storage, A: Api>(deps: &Extern<S, A>) -> Result<Vec<u8>> {
     serialize(&resp)
 }
 
+/// Only the beneficiary, owner, or oracle may view payout/ecostate data, and only with a
+/// viewing key whose hash matches the one stored for their address.
+fn authenticate<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    state: &State,
+    address: &HumanAddr,
+    key: &str,
+) -> Result<()> {
+    let canonical = deps.api.canonical_address(address)?;
+    ensure_authorized_viewer(state, &canonical)?;
+
+    let stored_hash = viewing_keys_read(&deps.storage)
+        .get(canonical.as_slice())
+        .unwrap_or_default();
+
+    if stored_hash.is_empty() || !ct_eq(&hash_viewing_key(key), &stored_hash) {
+        return unauthorized();
+    }
+
+    Ok(())
+}
+
+fn ensure_authorized_viewer(state: &State, address: &CanonicalAddr) -> Result<()> {
+    if address == &state.beneficiary || address == &state.owner || address == &state.oracle {
+        Ok(())
+    } else {
+        unauthorized()
+    }
+}
+
+fn query_vesting<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    address: HumanAddr,
+    key: String,
+) -> Result<Vec<u8>> {
+    let state = config_read(&deps.storage).load()?;
+    authenticate(deps, &state, &address, &key)?;
+
+    let resp = VestingResponse {
+        pending_release: state.pending_release,
+    };
+    serialize(&resp)
+}
+
+/// Returns history entries in the order they were appended, starting just after
+/// `start_after` (or from the beginning if `None`), capped at `MAX_HISTORY_LIMIT`.
+/// Gated by the same viewing key check as `GetState`/`GetEcostate`, since entries carry
+/// the same confidential ecostate/payout data.
+fn query_history<S: Storage, A: Api>(
+    deps: &Extern<S, A>,
+    address: HumanAddr,
+    key: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Vec<u8>> {
+    let state = config_read(&deps.storage).load()?;
+    authenticate(deps, &state, &address, &key)?;
+
+    let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT) as u64;
+    let start = start_after.map(|i| i.saturating_add(1)).unwrap_or(0);
+
+    let store = history_read(&deps.storage);
+    let mut entries = vec![];
+    for seq in start..state.history_len {
+        if entries.len() as u64 >= limit {
+            break;
+        }
+        if let Some(raw) = store.get(&seq.to_be_bytes()) {
+            entries.push(deserialize::<HistoryEntry>(&raw)?);
+        }
+    }
+
+    let resp = HistoryResponse { entries };
+    serialize(&resp)
+}
+
 fn valid_ecostate(ecostate: &i64) -> Result<i64> {
     if *ecostate >= 0 && *ecostate < 10000 {
         Ok(*ecostate)
@@ -174,11 +562,12 @@ fn valid_ecostate(ecostate: &i64) -> Result<i64> {
     }
 }
 
-fn check_lock(state: &State) -> Result<()> {
-    if state.is_locked {
-        contract_err("Contract is locked.")
-    } else {
-        Ok(())
+fn check_not_stopped(state: &State) -> Result<()> {
+    match &state.status {
+        ContractStatus::StopAll { reason } => {
+            contract_err(&format!("Contract is stopped: {}", reason))
+        }
+        _ => Ok(()),
     }
 }
 
@@ -189,7 +578,9 @@ mod tests {
     use cosmwasm::mock::{dependencies, mock_env};
     use cosmwasm::types::coin;
 
-    use cw_storage::deserialize;
+    use cw_storage::{deserialize, singleton};
+
+    use crate::state::{LegacyHistoryEntry, LegacyStateV1, CONFIG_KEY};
 
     fn init_helper<S: Storage, A: Api>(deps: &mut Extern<S, A>) -> Result<Response> {
         let msg = InitMsg {
@@ -197,7 +588,16 @@ mod tests {
             ecostate: 3500,
             oracle: HumanAddr::from("oracle"),
             region: String::from("angeles national forest"),
-            total_tokens: 100000,
+            total_tokens: Uint128::from(100_000u128),
+            denom: String::from("token"),
+            payout_curve: PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            start: 0,
+            cliff: 0,
+            deadline: u64::max_value(),
+            prng_seed: b"a very secret seed".to_vec(),
         };
 
         let env = mock_env(&deps.api, "creator", &coin("1000", "earth"), &[]);
@@ -206,30 +606,107 @@ mod tests {
         init(deps, env, msg)
     }
 
+    /// Like `init_helper`, but with the ecostate, payout curve, and vesting window
+    /// parameterized for tests that exercise a specific curve or vesting phase.
+    fn init_custom<S: Storage, A: Api>(
+        deps: &mut Extern<S, A>,
+        ecostate: i64,
+        payout_curve: PayoutCurve,
+        start: u64,
+        cliff: u64,
+        deadline: u64,
+    ) -> Result<Response> {
+        let msg = InitMsg {
+            beneficiary: HumanAddr::from("beneficiary"),
+            ecostate,
+            oracle: HumanAddr::from("oracle"),
+            region: String::from("angeles national forest"),
+            total_tokens: Uint128::from(100_000u128),
+            denom: String::from("token"),
+            payout_curve,
+            start,
+            cliff,
+            deadline,
+            prng_seed: b"a very secret seed".to_vec(),
+        };
+
+        let env = mock_env(&deps.api, "creator", &coin("1000", "earth"), &[]);
+
+        init(deps, env, msg)
+    }
+
+    fn set_viewing_key<S: Storage, A: Api>(
+        deps: &mut Extern<S, A>,
+        signer: &str,
+        key: &str,
+    ) {
+        let env = mock_env(&deps.api, signer, &coin("2", "token"), &[]);
+        let msg = HandleMsg::SetViewingKey {
+            key: String::from(key),
+        };
+        handle(deps, env, msg).unwrap();
+    }
+
     #[test]
     fn proper_initialization() {
         let mut deps = dependencies(20);
         let res = init_helper(&mut deps).unwrap();
         assert_eq!(0, res.messages.len());
 
-        // it worked, let's query the state
-        let res = query(&deps, QueryMsg::GetEcostate {}).unwrap();
+        // the owner must set a viewing key before the ecostate can be queried
+        set_viewing_key(&mut deps, "creator", "mykey");
+
+        let res = query(
+            &deps,
+            QueryMsg::GetEcostate {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
         let value: EcostateResponse = deserialize(&res).unwrap();
         assert_eq!(3500, value.ecostate);
+
+        // the wrong key is rejected
+        let res = query(
+            &deps,
+            QueryMsg::GetEcostate {
+                address: HumanAddr::from("creator"),
+                key: String::from("wrongkey"),
+            },
+        );
+        match res {
+            Err(Error::Unauthorized { .. }) => {}
+            _ => panic!("Wrong viewing key should not be accepted"),
+        }
     }
 
     #[test]
     fn ecostate_update_with_payout() {
         let mut deps = dependencies(20);
         let _res = init_helper(&mut deps).unwrap();
+        set_viewing_key(&mut deps, "creator", "mykey");
 
-        // oracle can update ecostate
-        let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
+        // oracle can update ecostate; the contract must hold enough escrow to pay out
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
         let msg = HandleMsg::UpdateEcostate { ecostate: 5000 };
-        let _res = handle(&mut deps, env, msg).unwrap();
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
 
         // ecostate should have updated successfully
-        let res = query(&deps, QueryMsg::GetEcostate {}).unwrap();
+        let res = query(
+            &deps,
+            QueryMsg::GetEcostate {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
         let value: EcostateResponse = deserialize(&res).unwrap();
         assert_eq!(5000, value.ecostate);
 
@@ -243,17 +720,25 @@ mod tests {
         }
 
         // payout should have completed successfully
-        let res = query(&deps, QueryMsg::GetState {}).unwrap();
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
         let value: StateResponse = deserialize(&res).unwrap();
         assert_eq!(5000, value.state.ecostate);
-        assert_eq!(98500, value.state.total_tokens);
-        assert_eq!(1500, value.state.released_tokens);
+        assert_eq!(Uint128::from(98_500u128), value.state.total_tokens);
+        assert_eq!(Uint128::from(1_500u128), value.state.released_tokens);
     }
 
     #[test]
     fn ecostate_update_no_payout() {
         let mut deps = dependencies(20);
         let _res = init_helper(&mut deps).unwrap();
+        set_viewing_key(&mut deps, "creator", "mykey");
 
         // oracle can update ecostate
         let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
@@ -261,10 +746,596 @@ mod tests {
         let _res = handle(&mut deps, env, msg).unwrap();
 
         // ecostate should have updated successfully, with no payout made
-        let res = query(&deps, QueryMsg::GetState {}).unwrap();
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
         let value: StateResponse = deserialize(&res).unwrap();
         assert_eq!(3000, value.state.ecostate);
-        assert_eq!(100000, value.state.total_tokens);
-        assert_eq!(0, value.state.released_tokens);
+        assert_eq!(Uint128::from(100_000u128), value.state.total_tokens);
+        assert_eq!(Uint128::default(), value.state.released_tokens);
+    }
+
+    #[test]
+    fn quadratic_curve_rewards_gains_near_the_top() {
+        let mut deps = dependencies(20);
+        let _res = init_custom(
+            &mut deps,
+            1000,
+            PayoutCurve::Quadratic {
+                rate_num: 1,
+                rate_den: 100,
+            },
+            0,
+            0,
+            u64::max_value(),
+        )
+        .unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 2000 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        // (2000^2 - 1000^2) / 100 = 30000
+        assert_eq!(Uint128::from(30_000u128), value.state.released_tokens);
+        assert_eq!(Uint128::from(70_000u128), value.state.total_tokens);
+    }
+
+    #[test]
+    fn exponential_curve_compounds_per_unit_gained() {
+        let mut deps = dependencies(20);
+        let _res = init_custom(
+            &mut deps,
+            2,
+            PayoutCurve::Exponential {
+                base_num: 2,
+                base_den: 1,
+            },
+            0,
+            0,
+            u64::max_value(),
+        )
+        .unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 5 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        // base 2/1 compounded 3 times (delta = 5 - 2): 1 -> 2 -> 4 -> 8
+        assert_eq!(Uint128::from(8u128), value.state.released_tokens);
+    }
+
+    #[test]
+    fn vesting_accrues_before_cliff_without_paying_out() {
+        let mut deps = dependencies(20);
+        let _res = init_custom(
+            &mut deps,
+            1000,
+            PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            0,
+            u64::max_value() - 1,
+            u64::max_value(),
+        )
+        .unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 3000 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetVesting {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: VestingResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::from(2_000u128), value.pending_release);
+    }
+
+    #[test]
+    fn vesting_with_overflowing_cliff_accrues_without_overflow_panic() {
+        let mut deps = dependencies(20);
+        // start + cliff overflows u64; this must be treated as "cliff never reached"
+        // rather than panicking.
+        let _res = init_custom(
+            &mut deps,
+            1000,
+            PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            10,
+            u64::max_value() - 5,
+            u64::max_value(),
+        )
+        .unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 3000 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetVesting {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: VestingResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::from(2_000u128), value.pending_release);
+    }
+
+    #[test]
+    fn vesting_shortfall_carries_forward_as_pending_release() {
+        let mut deps = dependencies(20);
+        let _res = init_custom(
+            &mut deps,
+            1000,
+            PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            0,
+            0,
+            u64::max_value(),
+        )
+        .unwrap();
+
+        // only 1000 of the 2000 owed is actually in escrow
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("1000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 3000 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+
+        // the undisbursed shortfall must still be owed, not discarded
+        let res = query(
+            &deps,
+            QueryMsg::GetVesting {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: VestingResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::from(1_000u128), value.pending_release);
+
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::from(99_000u128), value.state.total_tokens);
+        assert_eq!(Uint128::from(1_000u128), value.state.released_tokens);
+    }
+
+    #[test]
+    fn reclaim_after_deadline_returns_remaining_escrow() {
+        let mut deps = dependencies(20);
+        let _res = init_custom(
+            &mut deps,
+            3500,
+            PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "creator",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::Reclaim {};
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::default(), value.state.total_tokens);
+    }
+
+    #[test]
+    fn reclaim_before_deadline_rejected() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        let env = mock_env(&deps.api, "creator", &coin("2", "token"), &[]);
+        let msg = HandleMsg::Reclaim {};
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(Error::ContractErr { .. }) => {}
+            _ => panic!("Reclaim before the deadline should be rejected"),
+        }
+    }
+
+    #[test]
+    fn stop_payouts_blocks_disbursement_but_allows_ecostate_update() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        let env = mock_env(&deps.api, "creator", &coin("2", "token"), &[]);
+        let msg = HandleMsg::SetStatus {
+            status: ContractStatus::StopPayouts {
+                reason: String::from("pausing distributions"),
+            },
+        };
+        handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        let msg = HandleMsg::UpdateEcostate { ecostate: 5000 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        assert_eq!(5000, value.state.ecostate);
+        assert_eq!(Uint128::from(100_000u128), value.state.total_tokens);
+        assert_eq!(Uint128::default(), value.state.released_tokens);
+    }
+
+    #[test]
+    fn stop_all_blocks_ecostate_updates() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        let env = mock_env(&deps.api, "creator", &coin("2", "token"), &[]);
+        let msg = HandleMsg::SetStatus {
+            status: ContractStatus::StopAll {
+                reason: String::from("emergency freeze"),
+            },
+        };
+        handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
+        let msg = HandleMsg::UpdateEcostate { ecostate: 5000 };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(Error::ContractErr { .. }) => {}
+            _ => panic!("StopAll should block ecostate updates"),
+        }
+    }
+
+    #[test]
+    fn set_status_requires_owner() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        let env = mock_env(&deps.api, "anyone", &coin("2", "token"), &[]);
+        let msg = HandleMsg::SetStatus {
+            status: ContractStatus::StopAll {
+                reason: String::from("not allowed"),
+            },
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(Error::Unauthorized { .. }) => {}
+            _ => panic!("Only the owner may change contract status"),
+        }
+    }
+
+    #[test]
+    fn viewing_key_gates_queries() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        // querying before any viewing key is set is unauthorized
+        let res = query(
+            &deps,
+            QueryMsg::GetEcostate {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        );
+        match res {
+            Err(Error::Unauthorized { .. }) => {}
+            _ => panic!("Query without a viewing key should be unauthorized"),
+        }
+
+        // an address that is neither the beneficiary, owner, nor oracle is rejected
+        set_viewing_key(&mut deps, "stranger", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetEcostate {
+                address: HumanAddr::from("stranger"),
+                key: String::from("mykey"),
+            },
+        );
+        match res {
+            Err(Error::Unauthorized { .. }) => {}
+            _ => panic!("Query from an unrelated address should be unauthorized"),
+        }
+    }
+
+    #[test]
+    fn history_records_every_update() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+        set_viewing_key(&mut deps, "creator", "mykey");
+
+        let env = mock_env(
+            &deps.api,
+            "oracle",
+            &coin("2", "token"),
+            &coin("100000", "token"),
+        );
+        handle(&mut deps, env, HandleMsg::UpdateEcostate { ecostate: 5000 }).unwrap();
+
+        let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
+        handle(&mut deps, env, HandleMsg::UpdateEcostate { ecostate: 4000 }).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: HistoryResponse = deserialize(&res).unwrap();
+        assert_eq!(2, value.entries.len());
+        assert_eq!(3500, value.entries[0].old_ecostate);
+        assert_eq!(5000, value.entries[0].new_ecostate);
+        assert_eq!(Uint128::from(1_500u128), value.entries[0].payout_amount);
+        assert_eq!(5000, value.entries[1].old_ecostate);
+        assert_eq!(4000, value.entries[1].new_ecostate);
+        assert_eq!(Uint128::default(), value.entries[1].payout_amount);
+
+        // pagination: skip the first entry
+        let res = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+                start_after: Some(0),
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: HistoryResponse = deserialize(&res).unwrap();
+        assert_eq!(1, value.entries.len());
+        assert_eq!(4000, value.entries[0].new_ecostate);
+    }
+
+    #[test]
+    fn history_start_after_u64_max_returns_empty_without_overflow() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+        set_viewing_key(&mut deps, "creator", "mykey");
+
+        let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
+        handle(&mut deps, env, HandleMsg::UpdateEcostate { ecostate: 5000 }).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+                start_after: Some(u64::max_value()),
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: HistoryResponse = deserialize(&res).unwrap();
+        assert_eq!(0, value.entries.len());
+    }
+
+    #[test]
+    fn history_requires_viewing_key() {
+        let mut deps = dependencies(20);
+        let _res = init_helper(&mut deps).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+                start_after: None,
+                limit: None,
+            },
+        );
+        match res {
+            Err(Error::Unauthorized { .. }) => {}
+            _ => panic!("GetHistory without a viewing key should be unauthorized"),
+        }
+    }
+
+    #[test]
+    fn migrate_converts_legacy_i64_state_to_uint128() {
+        let mut deps = dependencies(20);
+
+        let legacy = LegacyStateV1 {
+            region: String::from("angeles national forest"),
+            beneficiary: deps.api.canonical_address(&HumanAddr::from("beneficiary")).unwrap(),
+            owner: deps.api.canonical_address(&HumanAddr::from("creator")).unwrap(),
+            oracle: deps.api.canonical_address(&HumanAddr::from("oracle")).unwrap(),
+            ecostate: 5000,
+            total_tokens: 98_500,
+            released_tokens: 1_500,
+            status: ContractStatus::Operational,
+            denom: String::from("token"),
+            payout_curve: PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            start: 0,
+            cliff: 0,
+            deadline: u64::max_value(),
+            pending_release: 0,
+            prng_seed: b"a very secret seed".to_vec(),
+            history_len: 0,
+        };
+        singleton(&mut deps.storage, CONFIG_KEY)
+            .save(&legacy)
+            .unwrap();
+
+        let env = mock_env(&deps.api, "creator", &coin("2", "token"), &[]);
+        migrate(&mut deps, env, MigrateMsg {}).unwrap();
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetState {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+            },
+        )
+        .unwrap();
+        let value: StateResponse = deserialize(&res).unwrap();
+        assert_eq!(Uint128::from(98_500u128), value.state.total_tokens);
+        assert_eq!(Uint128::from(1_500u128), value.state.released_tokens);
+        assert_eq!(Uint128::default(), value.state.pending_release);
+    }
+
+    #[test]
+    fn migrate_converts_legacy_history_entries_to_uint128() {
+        let mut deps = dependencies(20);
+
+        let legacy = LegacyStateV1 {
+            region: String::from("angeles national forest"),
+            beneficiary: deps.api.canonical_address(&HumanAddr::from("beneficiary")).unwrap(),
+            owner: deps.api.canonical_address(&HumanAddr::from("creator")).unwrap(),
+            oracle: deps.api.canonical_address(&HumanAddr::from("oracle")).unwrap(),
+            ecostate: 5000,
+            total_tokens: 98_500,
+            released_tokens: 1_500,
+            status: ContractStatus::Operational,
+            denom: String::from("token"),
+            payout_curve: PayoutCurve::Linear {
+                rate_num: 1,
+                rate_den: 1,
+            },
+            start: 0,
+            cliff: 0,
+            deadline: u64::max_value(),
+            pending_release: 0,
+            prng_seed: b"a very secret seed".to_vec(),
+            history_len: 1,
+        };
+        singleton(&mut deps.storage, CONFIG_KEY)
+            .save(&legacy)
+            .unwrap();
+
+        let legacy_entry = LegacyHistoryEntry {
+            height: 12345,
+            time: 67890,
+            oracle: legacy.oracle.clone(),
+            old_ecostate: 3500,
+            new_ecostate: 5000,
+            payout_amount: 1_500,
+        };
+        history(&mut deps.storage).set(&0u64.to_be_bytes(), &serialize(&legacy_entry).unwrap());
+
+        let env = mock_env(&deps.api, "creator", &coin("2", "token"), &[]);
+        migrate(&mut deps, env, MigrateMsg {}).unwrap();
+
+        set_viewing_key(&mut deps, "creator", "mykey");
+        let res = query(
+            &deps,
+            QueryMsg::GetHistory {
+                address: HumanAddr::from("creator"),
+                key: String::from("mykey"),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: HistoryResponse = deserialize(&res).unwrap();
+        assert_eq!(1, value.entries.len());
+        assert_eq!(Uint128::from(1_500u128), value.entries[0].payout_amount);
     }
 }
@@ -1,7 +1,7 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm::types::{HumanAddr};
-use crate::state::State;
+use cosmwasm::types::{HumanAddr, Uint128};
+use crate::state::{ContractStatus, HistoryEntry, PayoutCurve, State};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
@@ -9,24 +9,44 @@ pub struct InitMsg {
     pub beneficiary: HumanAddr,
     pub oracle: HumanAddr,
     pub ecostate: i64,
-    pub total_tokens: i64,
+    pub total_tokens: Uint128,
+    pub denom: String,
+    pub payout_curve: PayoutCurve,
+    pub start: u64,
+    pub cliff: u64,
+    pub deadline: u64,
+    pub prng_seed: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
     UpdateEcostate {ecostate: i64},
-    Lock {},
-    Unlock {},
+    SetStatus {status: ContractStatus},
     ChangeBeneficiary {beneficiary: HumanAddr},
     TransferOwnership {owner: HumanAddr},
+    Reclaim {},
+    SetViewingKey {key: String},
+    CreateViewingKey {entropy: String},
 }
 
+/// Empty for now: migrating a contract only rewrites its stored schema, with no
+/// caller-supplied parameters.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    GetState {},
-    GetEcostate {},
+    GetState {address: HumanAddr, key: String},
+    GetEcostate {address: HumanAddr, key: String},
+    GetVesting {address: HumanAddr, key: String},
+    GetHistory {
+        address: HumanAddr,
+        key: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -38,3 +58,18 @@ pub struct StateResponse {
 pub struct EcostateResponse {
     pub ecostate: i64,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingResponse {
+    pub pending_release: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ViewingKeyResponse {
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+}
@@ -2,13 +2,79 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm::traits::Storage;
-use cosmwasm::types::CanonicalAddr;
-use cw_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use cosmwasm::types::{CanonicalAddr, Uint128};
+use cw_storage::{
+    singleton, singleton_read, PrefixedStorage, ReadonlyPrefixedStorage, ReadonlySingleton,
+    Singleton,
+};
 
 pub static CONFIG_KEY: &[u8] = b"config";
+pub static VIEWING_KEY_PREFIX: &[u8] = b"viewing_key";
+pub static HISTORY_PREFIX: &[u8] = b"history";
+
+/// Graduated emergency-stop levels, from fully operational down to fully frozen.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    /// Ecostate updates and payouts proceed normally.
+    Operational,
+    /// The oracle can keep feeding ecostate readings, but disbursements are skipped.
+    StopPayouts { reason: String },
+    /// Every state-mutating handler is blocked except `SetStatus`, `SetViewingKey` and
+    /// `CreateViewingKey`, which still run.
+    StopAll { reason: String },
+}
+
+impl Default for ContractStatus {
+    fn default() -> Self {
+        ContractStatus::Operational
+    }
+}
+
+/// Selects how an ecostate gain is converted into a token payout.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutCurve {
+    /// `amount = delta * rate_num / rate_den`
+    Linear { rate_num: i64, rate_den: i64 },
+    /// `amount = (new^2 - old^2) * rate_num / rate_den`, rewarding gains near the top more.
+    Quadratic { rate_num: i64, rate_den: i64 },
+    /// `amount` compounds `base_num / base_den` once per unit of ecostate gained.
+    Exponential { base_num: i64, base_den: i64 },
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
+    pub region: String,
+    pub beneficiary: CanonicalAddr,
+    pub owner: CanonicalAddr,
+    pub oracle: CanonicalAddr,
+    pub ecostate: i64,
+    pub total_tokens: Uint128,
+    pub released_tokens: Uint128,
+    pub status: ContractStatus,
+    /// Native denom held in escrow by this contract and paid out to the beneficiary.
+    pub denom: String,
+    pub payout_curve: PayoutCurve,
+    /// Block time before which no disbursement occurs, regardless of ecostate gains.
+    pub start: u64,
+    /// Seconds after `start` before disbursements begin.
+    pub cliff: u64,
+    /// Block time after which unreleased escrow can be reclaimed by the owner.
+    pub deadline: u64,
+    /// Tokens owed from ecostate gains but not yet released: gains recorded before the cliff,
+    /// plus any amount a payout after the cliff could not disburse.
+    pub pending_release: Uint128,
+    /// Seed mixed into generated viewing keys; set once at init.
+    pub prng_seed: Vec<u8>,
+    /// Number of entries appended to the ecostate history log; doubles as the next sequence key.
+    pub history_len: u64,
+}
+
+/// The on-chain shape of [`State`] before the `i64` token fields were widened to `Uint128`.
+/// Only used by `contract::migrate` to upgrade contracts instantiated before that change.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyStateV1 {
     pub region: String,
     pub beneficiary: CanonicalAddr,
     pub owner: CanonicalAddr,
@@ -16,7 +82,38 @@ pub struct State {
     pub ecostate: i64,
     pub total_tokens: i64,
     pub released_tokens: i64,
-    pub is_locked: bool,
+    pub status: ContractStatus,
+    pub denom: String,
+    pub payout_curve: PayoutCurve,
+    pub start: u64,
+    pub cliff: u64,
+    pub deadline: u64,
+    pub pending_release: i64,
+    pub prng_seed: Vec<u8>,
+    pub history_len: u64,
+}
+
+/// One append-only record of an oracle-triggered ecostate update and the payout it caused.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HistoryEntry {
+    pub height: u64,
+    pub time: u64,
+    pub oracle: CanonicalAddr,
+    pub old_ecostate: i64,
+    pub new_ecostate: i64,
+    pub payout_amount: Uint128,
+}
+
+/// The on-chain shape of [`HistoryEntry`] before `payout_amount` was widened to `Uint128`.
+/// Only used by `contract::migrate` to upgrade history entries recorded before that change.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyHistoryEntry {
+    pub height: u64,
+    pub time: u64,
+    pub oracle: CanonicalAddr,
+    pub old_ecostate: i64,
+    pub new_ecostate: i64,
+    pub payout_amount: i64,
 }
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
@@ -26,3 +123,26 @@ pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
 pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+/// Reads the config slot under the pre-`Uint128` schema; only used during migration.
+pub fn config_legacy_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, LegacyStateV1> {
+    singleton_read(storage, CONFIG_KEY)
+}
+
+/// Keyed by the canonical address bytes; stores the SHA-256 hash of that address's viewing key.
+pub fn viewing_keys<S: Storage>(storage: &mut S) -> PrefixedStorage<S> {
+    PrefixedStorage::new(VIEWING_KEY_PREFIX, storage)
+}
+
+pub fn viewing_keys_read<S: Storage>(storage: &S) -> ReadonlyPrefixedStorage<S> {
+    ReadonlyPrefixedStorage::new(VIEWING_KEY_PREFIX, storage)
+}
+
+/// Keyed by big-endian sequence number, so entries iterate in the order they were appended.
+pub fn history<S: Storage>(storage: &mut S) -> PrefixedStorage<S> {
+    PrefixedStorage::new(HISTORY_PREFIX, storage)
+}
+
+pub fn history_read<S: Storage>(storage: &S) -> ReadonlyPrefixedStorage<S> {
+    ReadonlyPrefixedStorage::new(HISTORY_PREFIX, storage)
+}
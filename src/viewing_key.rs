@@ -0,0 +1,32 @@
+//! SHA-256 viewing key hashing and generation, SNIP20-style.
+
+use sha2::{Digest, Sha256};
+
+pub const VIEWING_KEY_PREFIX: &str = "api_key_";
+
+pub fn hash_viewing_key(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+pub fn generate_viewing_key(prng_seed: &[u8], entropy: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prng_seed);
+    hasher.update(entropy);
+    format!("{}{}", VIEWING_KEY_PREFIX, to_hex(&hasher.finalize()))
+}
+
+/// Constant-time comparison so key-guessing can't be timed out of the hash check.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -28,14 +28,17 @@
 //!      }
 
 use cosmwasm::mock::{mock_env, MockStorage, MockApi};
-use cosmwasm::types::{coin, ContractResult, HumanAddr};
+use cosmwasm::types::{coin, ContractResult, HumanAddr, Uint128};
 
 use cosmwasm_vm::Instance;
 use cosmwasm_vm::testing::{handle, init, mock_instance, query};
 
 use cw_storage::deserialize;
 
-use ecodata_payout::msg::{EcostateResponse, HandleMsg, InitMsg, QueryMsg, StateResponse};
+use ecodata_payout::msg::{
+    EcostateResponse, HandleMsg, HistoryResponse, InitMsg, QueryMsg, StateResponse,
+};
+use ecodata_payout::state::PayoutCurve;
 
 // This line will test the output of cargo wasm
 static WASM: &[u8] = include_bytes!("../target/wasm32-unknown-unknown/release/ecodata_payout.wasm");
@@ -49,7 +52,16 @@ fn init_helper(deps: &mut Instance<MockStorage, MockApi>) -> ContractResult {
         ecostate: 3500,
         oracle: HumanAddr::from("oracle"),
         region: String::from("angeles national forest"),
-        total_tokens: 100000,
+        total_tokens: Uint128::from(100_000u128),
+        denom: String::from("token"),
+        payout_curve: PayoutCurve::Linear {
+            rate_num: 1,
+            rate_den: 1,
+        },
+        start: 0,
+        cliff: 0,
+        deadline: u64::max_value(),
+        prng_seed: b"a very secret seed".to_vec(),
     };
 
     let env = mock_env(&deps.api, "creator", &coin("1000", "earth"), &[]);
@@ -58,14 +70,31 @@ fn init_helper(deps: &mut Instance<MockStorage, MockApi>) -> ContractResult {
     init(deps, env, msg)
 }
 
+fn set_viewing_key(deps: &mut Instance<MockStorage, MockApi>, signer: &str, key: &str) {
+    let env = mock_env(&deps.api, signer, &coin("2", "token"), &[]);
+    let msg = HandleMsg::SetViewingKey {
+        key: String::from(key),
+    };
+    handle(deps, env, msg).unwrap();
+}
+
 #[test]
 fn proper_initialization() {
     let mut deps = mock_instance(WASM);
     let res = init_helper(&mut deps).unwrap();
     assert_eq!(0, res.messages.len());
 
-    // it worked, let's query the state
-    let res = query(&mut deps, QueryMsg::GetEcostate {}).unwrap();
+    // the owner must set a viewing key before the ecostate can be queried
+    set_viewing_key(&mut deps, "creator", "mykey");
+
+    let res = query(
+        &mut deps,
+        QueryMsg::GetEcostate {
+            address: HumanAddr::from("creator"),
+            key: String::from("mykey"),
+        },
+    )
+    .unwrap();
     let value: EcostateResponse = deserialize(res.as_slice()).unwrap();
     assert_eq!(3500, value.ecostate);
 }
@@ -74,14 +103,28 @@ fn proper_initialization() {
 fn ecostate_update_with_payout() {
     let mut deps = mock_instance(WASM);
     let _res = init_helper(&mut deps).unwrap();
-
-    // oracle can update ecostate
-    let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
+    set_viewing_key(&mut deps, "creator", "mykey");
+
+    // oracle can update ecostate; the contract must hold enough escrow to pay out
+    let env = mock_env(
+        &deps.api,
+        "oracle",
+        &coin("2", "token"),
+        &coin("100000", "token"),
+    );
     let msg = HandleMsg::UpdateEcostate { ecostate: 5000 };
-    let _res = handle(&mut deps, env, msg).unwrap();
+    let res = handle(&mut deps, env, msg).unwrap();
+    assert_eq!(1, res.messages.len());
 
     // ecostate should have updated successfully
-    let res = query(&mut deps, QueryMsg::GetEcostate {}).unwrap();
+    let res = query(
+        &mut deps,
+        QueryMsg::GetEcostate {
+            address: HumanAddr::from("creator"),
+            key: String::from("mykey"),
+        },
+    )
+    .unwrap();
     let value: EcostateResponse = deserialize(res.as_slice()).unwrap();
     assert_eq!(5000, value.ecostate);
 
@@ -95,17 +138,25 @@ fn ecostate_update_with_payout() {
     }
 
     // payout should have completed successfully
-    let res = query(&mut deps, QueryMsg::GetState {}).unwrap();
+    let res = query(
+        &mut deps,
+        QueryMsg::GetState {
+            address: HumanAddr::from("creator"),
+            key: String::from("mykey"),
+        },
+    )
+    .unwrap();
     let value: StateResponse = deserialize(res.as_slice()).unwrap();
     assert_eq!(5000, value.state.ecostate);
-    assert_eq!(98500, value.state.total_tokens);
-    assert_eq!(1500, value.state.released_tokens);
+    assert_eq!(Uint128::from(98_500u128), value.state.total_tokens);
+    assert_eq!(Uint128::from(1_500u128), value.state.released_tokens);
 }
 
 #[test]
 fn ecostate_update_no_payout() {
     let mut deps = mock_instance(WASM);
     let _res = init_helper(&mut deps).unwrap();
+    set_viewing_key(&mut deps, "creator", "mykey");
 
     // oracle can update ecostate
     let env = mock_env(&deps.api, "oracle", &coin("2", "token"), &[]);
@@ -113,9 +164,48 @@ fn ecostate_update_no_payout() {
     let _res = handle(&mut deps, env, msg).unwrap();
 
     // ecostate should have updated successfully, with no payout made
-    let res = query(&mut deps, QueryMsg::GetState {}).unwrap();
+    let res = query(
+        &mut deps,
+        QueryMsg::GetState {
+            address: HumanAddr::from("creator"),
+            key: String::from("mykey"),
+        },
+    )
+    .unwrap();
     let value: StateResponse = deserialize(res.as_slice()).unwrap();
     assert_eq!(3000, value.state.ecostate);
-    assert_eq!(100000, value.state.total_tokens);
-    assert_eq!(0, value.state.released_tokens);
+    assert_eq!(Uint128::from(100_000u128), value.state.total_tokens);
+    assert_eq!(Uint128::default(), value.state.released_tokens);
+}
+
+#[test]
+fn history_records_every_update() {
+    let mut deps = mock_instance(WASM);
+    let _res = init_helper(&mut deps).unwrap();
+    set_viewing_key(&mut deps, "creator", "mykey");
+
+    let env = mock_env(
+        &deps.api,
+        "oracle",
+        &coin("2", "token"),
+        &coin("100000", "token"),
+    );
+    let msg = HandleMsg::UpdateEcostate { ecostate: 5000 };
+    handle(&mut deps, env, msg).unwrap();
+
+    let res = query(
+        &mut deps,
+        QueryMsg::GetHistory {
+            address: HumanAddr::from("creator"),
+            key: String::from("mykey"),
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    let value: HistoryResponse = deserialize(res.as_slice()).unwrap();
+    assert_eq!(1, value.entries.len());
+    assert_eq!(3500, value.entries[0].old_ecostate);
+    assert_eq!(5000, value.entries[0].new_ecostate);
+    assert_eq!(Uint128::from(1_500u128), value.entries[0].payout_amount);
 }